@@ -5,17 +5,19 @@
 //! in big-endian form with no packing.
 
 use bincode::Options;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::io::{Read, Write};
 use thiserror::Error;
 
 /// An error that occurred during a deserialize operation
-///
-/// This could happen because the input data was too short,
-/// or because an invalid value was encountered.
-#[derive(Debug, Error)]
-#[error("deserialize error")]
-pub struct DeserializeError;
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The input data was too short, an invalid value was encountered,
+    /// or a size limit was exceeded
+    #[error("deserialize error")]
+    Invalid,
+}
 
 /// An error that occurred during a serialize operation
 ///
@@ -25,6 +27,24 @@ pub struct DeserializeError;
 #[error("serialize error")]
 pub struct SerializeError;
 
+/// A [`Read`] wrapper that counts the bytes passed through it
+///
+/// Used to report how many bytes a streaming deserialize consumed,
+/// so a caller can advance a cursor over a buffer holding several
+/// concatenated messages.
+struct CountingReader<R> {
+    inner: R,
+    count: usize,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+}
+
 /// A shortcut that defines our method of binary serialization
 ///
 /// Properties:
@@ -40,8 +60,31 @@ pub fn coder() -> impl Options {
         .allow_trailing_bytes()
 }
 
-/// Binary serialize/deserialize helper functions
+/// A shortcut that defines our method of little-endian binary serialization
 ///
+/// Properties:
+/// - Little endian
+/// - Fixed integer encoding (i.e. 1u32 is 01000000 not 00000001)
+/// - Allow trailing bytes: this means we don't throw an error
+///   if the deserializer is passed a buffer with more data
+///   past the end.
+pub fn le_coder() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+/// Binary serialize/deserialize helper functions, using big-endian encoding
+///
+/// [`LeSerDe`] is the little-endian counterpart. Its methods share the
+/// same names as this trait's on purpose: if a module brings both traits
+/// into scope, every call site becomes ambiguous ("multiple applicable
+/// items in scope") and fails to compile, rather than silently picking
+/// one endianness.
+///
+/// See [`BinSerDeOwned`] for streaming/owned deserialize methods such as
+/// `bdes_from` and `bdes_limited`.
 pub trait BinSerDe<'de>: Serialize + Deserialize<'de> + Sized {
     /// Serialize into an existing buffer
     ///
@@ -52,6 +95,12 @@ pub trait BinSerDe<'de>: Serialize + Deserialize<'de> + Sized {
     fn bser(&self) -> Result<Vec<u8>, SerializeError>;
 
     /// Deserialize
+    ///
+    /// Any `#[serde(borrow)]` byte-slice field in `Self` borrows directly
+    /// from `buf` rather than being copied, thanks to the shared `'de`
+    /// lifetime; this already gives zero-copy decoding for large,
+    /// already-resident data such as a memory-mapped file, with no
+    /// separate entry point needed.
     fn bdes(buf: &'de [u8]) -> Result<Self, DeserializeError>;
 }
 
@@ -71,8 +120,263 @@ where
 
     /// Deserialize
     fn bdes(buf: &'de [u8]) -> Result<Self, DeserializeError> {
-        coder().deserialize(buf).or(Err(DeserializeError))
+        coder().deserialize(buf).or(Err(DeserializeError::Invalid))
+    }
+}
+
+// These methods are bounded on `DeserializeOwned` rather than `BinSerDe`'s
+// `'de` lifetime, because a value read from a `Read` stream can't borrow
+// from it; a per-method `where Self: DeserializeOwned` bound can't live on
+// `BinSerDe` itself without leaving the compiler unable to resolve its
+// `Deserialize<'de>` supertrait (E0283), so they get their own trait.
+//
+// `bdes_limited` lives here rather than on `BinSerDe` for a second reason:
+// bincode 1.3's slice-oriented `Options::deserialize` unconditionally
+// discards any configured `.with_limit()`, so enforcing it requires going
+// through the `Read`-based `deserialize_from` path used here.
+
+/// Streaming/owned deserialize helpers that pair with [`BinSerDe`]'s
+/// big-endian encoding
+///
+/// As with [`BinSerDe`]/[`LeSerDe`], this trait's little-endian
+/// counterpart ([`LeSerDeOwned`]) reuses the same method names on
+/// purpose, so mixing both in scope is a compile error rather than a
+/// silent choice of endianness.
+pub trait BinSerDeOwned: Serialize + DeserializeOwned + Sized {
+    /// Deserialize by streaming from a [`Read`]
+    ///
+    /// This avoids having to buffer the whole frame in memory before
+    /// decoding.
+    fn bdes_from<R: Read>(r: R) -> Result<Self, DeserializeError>;
+
+    /// Deserialize by streaming from a [`Read`], also returning the number
+    /// of bytes consumed
+    ///
+    /// Useful when `r` may hold several concatenated messages: the byte
+    /// count lets the caller advance past exactly this one and start
+    /// reading the next.
+    fn bdes_from_count<R: Read>(r: R) -> Result<(Self, usize), DeserializeError>;
+
+    /// Deserialize, but abort with a [`DeserializeError`] rather than
+    /// allocate once more than `max_bytes` total would be consumed
+    ///
+    /// Use this for untrusted input: a hostile length prefix on a `Vec`,
+    /// `String`, or map would otherwise trigger an allocation before any
+    /// of the corresponding data has even arrived.
+    fn bdes_limited(buf: &[u8], max_bytes: u64) -> Result<Self, DeserializeError>;
+
+    /// Deserialize by streaming from a [`Read`], with the same `max_bytes`
+    /// allocation budget as [`bdes_limited`](Self::bdes_limited)
+    fn bdes_from_limited<R: Read>(r: R, max_bytes: u64) -> Result<Self, DeserializeError>;
+}
+
+impl<T> BinSerDeOwned for T
+where
+    T: Serialize + DeserializeOwned + Sized,
+{
+    /// Deserialize by streaming from a [`Read`]
+    fn bdes_from<R: Read>(r: R) -> Result<Self, DeserializeError> {
+        coder()
+            .deserialize_from(r)
+            .or(Err(DeserializeError::Invalid))
+    }
+
+    /// Deserialize by streaming from a [`Read`], also returning the number
+    /// of bytes consumed
+    fn bdes_from_count<R: Read>(r: R) -> Result<(Self, usize), DeserializeError> {
+        let mut r = CountingReader { inner: r, count: 0 };
+        let value = coder()
+            .deserialize_from(&mut r)
+            .or(Err(DeserializeError::Invalid))?;
+        Ok((value, r.count))
+    }
+
+    /// Deserialize with an allocation budget
+    fn bdes_limited(buf: &[u8], max_bytes: u64) -> Result<Self, DeserializeError> {
+        coder()
+            .with_limit(max_bytes)
+            .deserialize_from(buf)
+            .or(Err(DeserializeError::Invalid))
+    }
+
+    /// Deserialize by streaming from a [`Read`], with an allocation budget
+    fn bdes_from_limited<R: Read>(r: R, max_bytes: u64) -> Result<Self, DeserializeError> {
+        coder()
+            .with_limit(max_bytes)
+            .deserialize_from(r)
+            .or(Err(DeserializeError::Invalid))
+    }
+}
+
+/// Binary serialize/deserialize helper functions, using little-endian encoding
+///
+/// This is the little-endian counterpart to [`BinSerDe`]; see that trait's
+/// docs for why the two share identical method names. See
+/// [`LeSerDeOwned`] for streaming/owned deserialize methods.
+pub trait LeSerDe<'de>: Serialize + Deserialize<'de> + Sized {
+    /// Serialize into an existing buffer
+    ///
+    /// tip: `&mut [u8]` implements `Write`
+    fn bser_into<W: Write>(&self, w: W) -> Result<(), SerializeError>;
+
+    /// Serialize into a new buffer
+    fn bser(&self) -> Result<Vec<u8>, SerializeError>;
+
+    /// Deserialize
+    ///
+    /// See [`BinSerDe::bdes`] for why this already borrows `#[serde(borrow)]`
+    /// fields from `buf` with no copy.
+    fn bdes(buf: &'de [u8]) -> Result<Self, DeserializeError>;
+}
+
+impl<'de, T> LeSerDe<'de> for T
+where
+    T: Serialize + Deserialize<'de> + Sized,
+{
+    /// Serialize into an existing buffer
+    fn bser_into<W: Write>(&self, w: W) -> Result<(), SerializeError> {
+        le_coder().serialize_into(w, &self).or(Err(SerializeError))
+    }
+
+    /// Serialize into a new heap-allocated buffer
+    fn bser(&self) -> Result<Vec<u8>, SerializeError> {
+        le_coder().serialize(&self).or(Err(SerializeError))
+    }
+
+    /// Deserialize
+    fn bdes(buf: &'de [u8]) -> Result<Self, DeserializeError> {
+        le_coder()
+            .deserialize(buf)
+            .or(Err(DeserializeError::Invalid))
+    }
+}
+
+/// Streaming/owned deserialize helpers that pair with [`LeSerDe`]'s
+/// little-endian encoding
+///
+/// See [`BinSerDeOwned`] for why these are split out from [`LeSerDe`].
+pub trait LeSerDeOwned: Serialize + DeserializeOwned + Sized {
+    /// Deserialize by streaming from a [`Read`]
+    fn bdes_from<R: Read>(r: R) -> Result<Self, DeserializeError>;
+
+    /// Deserialize by streaming from a [`Read`], also returning the number
+    /// of bytes consumed
+    fn bdes_from_count<R: Read>(r: R) -> Result<(Self, usize), DeserializeError>;
+
+    /// Deserialize, but abort with a [`DeserializeError`] rather than
+    /// allocate once more than `max_bytes` total would be consumed
+    fn bdes_limited(buf: &[u8], max_bytes: u64) -> Result<Self, DeserializeError>;
+
+    /// Deserialize by streaming from a [`Read`], with the same `max_bytes`
+    /// allocation budget as [`bdes_limited`](Self::bdes_limited)
+    fn bdes_from_limited<R: Read>(r: R, max_bytes: u64) -> Result<Self, DeserializeError>;
+}
+
+impl<T> LeSerDeOwned for T
+where
+    T: Serialize + DeserializeOwned + Sized,
+{
+    /// Deserialize by streaming from a [`Read`]
+    fn bdes_from<R: Read>(r: R) -> Result<Self, DeserializeError> {
+        le_coder()
+            .deserialize_from(r)
+            .or(Err(DeserializeError::Invalid))
+    }
+
+    /// Deserialize by streaming from a [`Read`], also returning the number
+    /// of bytes consumed
+    fn bdes_from_count<R: Read>(r: R) -> Result<(Self, usize), DeserializeError> {
+        let mut r = CountingReader { inner: r, count: 0 };
+        let value = le_coder()
+            .deserialize_from(&mut r)
+            .or(Err(DeserializeError::Invalid))?;
+        Ok((value, r.count))
     }
+
+    /// Deserialize with an allocation budget
+    fn bdes_limited(buf: &[u8], max_bytes: u64) -> Result<Self, DeserializeError> {
+        le_coder()
+            .with_limit(max_bytes)
+            .deserialize_from(buf)
+            .or(Err(DeserializeError::Invalid))
+    }
+
+    /// Deserialize by streaming from a [`Read`], with an allocation budget
+    fn bdes_from_limited<R: Read>(r: R, max_bytes: u64) -> Result<Self, DeserializeError> {
+        le_coder()
+            .with_limit(max_bytes)
+            .deserialize_from(r)
+            .or(Err(DeserializeError::Invalid))
+    }
+}
+
+/// Define an externally-tagged enum whose wire format is a single tag byte
+/// followed by the selected variant's body
+///
+/// This is the common wire pattern for tagged message unions, e.g.
+/// Postgres logical replication, where one byte (`b'k'`, `b'w'`, ...)
+/// selects among several message shapes. Name each variant's body type
+/// (which must implement [`BinSerDe`]) and the tag byte that picks it:
+///
+/// ```
+/// # use binserde::{tagged_enum, BinSerDe};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// pub struct KeepAlive { pub blockpos: u64 }
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// pub struct XLogData { pub blockpos: u64, pub data_len: u32 }
+///
+/// tagged_enum! {
+///     #[derive(Debug, PartialEq)]
+///     pub enum ReplicationMsg {
+///         KeepAlive(KeepAlive) = b'k',
+///         XLogData(XLogData) = b'w',
+///     }
+/// }
+/// ```
+///
+/// generates `bser`/`bdes` methods that write/read the one tag byte and
+/// delegate the rest to the body's own `BinSerDe` implementation,
+/// returning [`DeserializeError::Invalid`] for an unrecognized tag.
+#[macro_export]
+macro_rules! tagged_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($variant:ident($body:ty) = $tag:literal),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($variant($body)),+
+        }
+
+        impl $name {
+            /// Serialize into a new buffer: the tag byte, then the variant body
+            pub fn bser(&self) -> ::std::result::Result<::std::vec::Vec<u8>, $crate::SerializeError> {
+                let (tag, body): (u8, ::std::vec::Vec<u8>) = match self {
+                    $($name::$variant(body) => ($tag, $crate::BinSerDe::bser(body)?)),+
+                };
+                let mut buf = ::std::vec![tag];
+                buf.extend_from_slice(&body);
+                ::std::result::Result::Ok(buf)
+            }
+
+            /// Deserialize: read the tag byte, then dispatch to the matching variant
+            pub fn bdes(buf: &[u8]) -> ::std::result::Result<Self, $crate::DeserializeError> {
+                let (tag, rest) = buf
+                    .split_first()
+                    .ok_or($crate::DeserializeError::Invalid)?;
+                match *tag {
+                    $($tag => ::std::result::Result::Ok(
+                        $name::$variant($crate::BinSerDe::bdes(rest)?)
+                    )),+,
+                    _ => ::std::result::Result::Err($crate::DeserializeError::Invalid),
+                }
+            }
+        }
+    };
 }
 
 #[cfg(test)]
@@ -89,7 +393,7 @@ mod tests {
     fn short() {
         let x = ShortStruct { a: 7, b: 65536 };
 
-        let encoded = x.bser().unwrap();
+        let encoded = BinSerDe::bser(&x).unwrap();
 
         assert_eq!(encoded, vec![7, 0, 1, 0, 0]);
 
@@ -138,6 +442,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn little_endian() {
+        let x = ShortStruct { a: 7, b: 65536 };
+
+        let encoded = LeSerDe::bser(&x).unwrap();
+
+        assert_eq!(encoded, vec![7, 0, 0, 1, 0]);
+
+        let raw = [8u8, 0, 0, 3, 7];
+        let decoded: ShortStruct = le_coder().deserialize(&raw).unwrap();
+
+        assert_eq!(
+            decoded,
+            ShortStruct {
+                a: 8,
+                b: 0x07030000
+            }
+        );
+    }
+
     #[test]
     fn keepalive_reply() {
         let msg = KeepAliveReply {
@@ -146,7 +470,7 @@ mod tests {
         };
         let msg = PgReplicationMsg::from(msg);
 
-        let encoded = msg.bser().unwrap();
+        let encoded = BinSerDe::bser(&msg).unwrap();
 
         #[rustfmt::skip] // organize the bytes one field at a time.
         let expected = [
@@ -162,4 +486,108 @@ mod tests {
 
         assert_eq!(encoded, expected);
     }
+
+    #[test]
+    fn bdes_from_count() {
+        let msg = KeepAliveReply {
+            blockpos: 0x1234,
+            timestamp: 0x5678,
+        };
+        let msg = PgReplicationMsg::from(msg);
+        let mut encoded = BinSerDe::bser(&msg).unwrap();
+
+        // simulate a second message following the first in the buffer
+        encoded.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+        let (decoded, count): (PgReplicationMsg, usize) =
+            BinSerDeOwned::bdes_from_count(encoded.as_slice()).unwrap();
+
+        assert_eq!(decoded, msg);
+        assert_eq!(count, 34);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct BorrowedView<'a> {
+        pub kind: u8,
+        #[serde(borrow)]
+        pub payload: &'a [u8],
+    }
+
+    #[test]
+    fn bdes_borrowed() {
+        let x = BorrowedView {
+            kind: 1,
+            payload: &[9, 8, 7],
+        };
+        let encoded = BinSerDe::bser(&x).unwrap();
+
+        let decoded: BorrowedView = BinSerDe::bdes(&encoded).unwrap();
+        assert_eq!(decoded, x);
+        // the payload borrows straight from `encoded`, no copy (1 byte for
+        // `kind` + 8 bytes for the slice's u64 length prefix)
+        assert_eq!(decoded.payload.as_ptr(), encoded[9..].as_ptr());
+    }
+
+    #[test]
+    fn bdes_limited() {
+        // A `Vec<u8>` with a hostile length prefix claiming 2^32 - 1 bytes
+        // follow, when in fact none do: rejected immediately on EOF,
+        // regardless of the limit.
+        let hostile = [0u8, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+
+        let result: Result<Vec<u8>, _> = BinSerDeOwned::bdes_limited(&hostile, 16);
+        assert!(result.is_err());
+
+        // A length prefix that is genuinely present in full, but claims
+        // more bytes than the budget allows: the limit itself must reject
+        // this, not EOF, since all 20 bytes it asks for are right there.
+        let mut over_budget = vec![0u8, 0, 0, 0, 0, 0, 0, 20];
+        over_budget.extend_from_slice(&[0u8; 20]);
+
+        let result: Result<Vec<u8>, _> = BinSerDeOwned::bdes_limited(&over_budget, 16);
+        assert!(result.is_err());
+
+        // A reasonable length prefix, well within budget, still works.
+        let reasonable = [0u8, 0, 0, 0, 0, 0, 0, 2, 7, 9];
+        let decoded: Vec<u8> = BinSerDeOwned::bdes_limited(&reasonable, 16).unwrap();
+        assert_eq!(decoded, vec![7, 9]);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct KeepAliveBody {
+        pub blockpos: u64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct XLogDataBody {
+        pub blockpos: u64,
+        pub data_len: u32,
+    }
+
+    tagged_enum! {
+        #[derive(Debug, PartialEq)]
+        pub enum ReplicationMsg {
+            KeepAlive(KeepAliveBody) = b'k',
+            XLogData(XLogDataBody) = b'w',
+        }
+    }
+
+    #[test]
+    fn tagged_dispatch() {
+        let msg = ReplicationMsg::XLogData(XLogDataBody {
+            blockpos: 0x1234,
+            data_len: 7,
+        });
+
+        let encoded = msg.bser().unwrap();
+        assert_eq!(encoded[0], b'w');
+
+        let decoded = ReplicationMsg::bdes(&encoded).unwrap();
+        assert_eq!(decoded, msg);
+
+        // unknown tag byte
+        let mut bogus = encoded;
+        bogus[0] = b'?';
+        assert!(ReplicationMsg::bdes(&bogus).is_err());
+    }
 }